@@ -1,7 +1,9 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::Range;
+use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Mutex;
 use std::sync::{Arc, RwLock};
-use std::{thread, vec};
+use std::{fs, thread, vec};
 
 use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
@@ -10,82 +12,321 @@ use bevy::math::ivec2;
 use bevy::prelude::*;
 use bevy::sprite;
 
-use crate::atom::State;
+use crate::atom::{Atom, State};
 use crate::chunk::*;
 use crate::consts::*;
 use crate::grid_api::*;
+use crate::materials::{MaterialRegistry, MaterialRegistryHandle};
 
 use std::cmp;
 
+/// Number of persistent worker threads kept alive for the whole run of the
+/// simulation, avoiding a `thread::spawn`/join per chunk per tick.
+const WORKER_COUNT: usize = 8;
+
+/// A single chunk's worth of update work handed off to a pool worker.
+struct WorkItem {
+    chunks: UpdateChunksType,
+    dt: f32,
+    registry: Arc<MaterialRegistry>,
+}
+
+/// Extracts a human-readable message from a caught panic payload, falling
+/// back to a generic message for payloads that aren't a `&str`/`String`
+/// (the two types `panic!`/`.unwrap()` actually produce).
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic payload")
+}
+
+/// Handle to a persistent worker thread: a channel to send it work, plus the
+/// thread itself so the pool can be torn down if the app exits.
+struct Worker {
+    sender: Sender<WorkItem>,
+    _handle: thread::JoinHandle<()>,
+}
+
+/// Pool of persistent threads that `grid_update` hands chunk work to instead
+/// of spawning a thread per chunk per tick. Workers report completion back
+/// over `done_sender`/`done_receiver`, handing back the now-idle scratch
+/// buffer so the main thread can refill and redispatch it next tick instead
+/// of allocating a new one.
+pub struct WorkerPool {
+    workers: Vec<Worker>,
+    done_sender: Sender<(usize, UpdateChunksType)>,
+    done_receiver: Receiver<(usize, UpdateChunksType)>,
+    // Per-worker scratch buffer, `None` while the worker has it checked out.
+    scratch: Vec<Option<UpdateChunksType>>,
+}
+
+impl WorkerPool {
+    fn new(count: usize) -> Self {
+        let (done_sender, done_receiver) = std::sync::mpsc::channel();
+
+        let workers = (0..count)
+            .map(|id| {
+                let (sender, receiver) = std::sync::mpsc::channel::<WorkItem>();
+                let done_sender = done_sender.clone();
+
+                let handle = thread::spawn(move || {
+                    while let Ok(work) = receiver.recv() {
+                        let WorkItem {
+                            chunks,
+                            dt,
+                            registry,
+                        } = work;
+
+                        // Workers are reused for the whole run of the sim
+                        // instead of respawned per tick, so a panic here
+                        // must not kill the thread: that would leave its
+                        // slot permanently unable to report completion and
+                        // freeze every future `wait_all`/`acquire_idle` on
+                        // it. Catch it,
+                        // log it, and still report done with whatever state
+                        // the scratch buffer ended up in.
+                        if let Err(payload) = std::panic::catch_unwind(
+                            std::panic::AssertUnwindSafe(|| update_chunks(&chunks, dt, &registry)),
+                        ) {
+                            error!("worker {id} panicked updating chunks: {}", panic_message(&payload));
+                        }
+
+                        done_sender.send((id, chunks)).unwrap();
+                    }
+                });
+
+                Worker {
+                    sender,
+                    _handle: handle,
+                }
+            })
+            .collect();
+
+        Self {
+            workers,
+            done_sender,
+            done_receiver,
+            scratch: (0..count).map(|_| Some(Vec::with_capacity(9))).collect(),
+        }
+    }
+
+    /// Returns the index of an idle worker, blocking on a completion if every
+    /// worker is currently busy. Dispatch must only ever go through this, so
+    /// a phase with more chunks than `WORKER_COUNT` waits for a slot to free
+    /// up instead of silently re-dispatching onto one that's still checked
+    /// out (which would allocate a fresh scratch buffer and then drop the
+    /// reclaimed one on completion).
+    fn acquire_idle(&mut self) -> usize {
+        if let Some(idx) = self.scratch.iter().position(Option::is_some) {
+            return idx;
+        }
+        let (worker_idx, buf) = self.done_receiver.recv().unwrap();
+        self.scratch[worker_idx] = Some(buf);
+        worker_idx
+    }
+
+    /// Takes back a worker's scratch buffer, ready to be refilled for the
+    /// next chunk dispatched to that worker slot. Only valid for a slot just
+    /// returned by `acquire_idle`.
+    fn take_scratch(&mut self, worker_idx: usize) -> UpdateChunksType {
+        let mut buf = self.scratch[worker_idx]
+            .take()
+            .expect("take_scratch called on a slot that isn't idle");
+        buf.clear();
+        buf
+    }
+
+    /// Dispatches `chunks` to the worker owning slot `worker_idx`.
+    fn dispatch(
+        &mut self,
+        worker_idx: usize,
+        chunks: UpdateChunksType,
+        dt: f32,
+        registry: Arc<MaterialRegistry>,
+    ) {
+        self.workers[worker_idx]
+            .sender
+            .send(WorkItem {
+                chunks,
+                dt,
+                registry,
+            })
+            .unwrap();
+    }
+
+    /// Blocks until every worker has reported completion, reclaiming each
+    /// one's scratch buffer - used between phases to preserve the
+    /// checkerboard data-race guarantees.
+    fn wait_all(&mut self) {
+        while self.scratch.iter().any(Option::is_none) {
+            let (worker_idx, buf) = self.done_receiver.recv().unwrap();
+            self.scratch[worker_idx] = Some(buf);
+        }
+    }
+}
+
+/// Chunks within this many chunks of the camera are kept loaded; chunks that
+/// fall outside it are serialized to disk and dropped from the map.
+const STREAM_RADIUS: i32 = 4;
+
+/// Directory unloaded chunks are persisted to/read back from, keyed by `CPos`.
+const WORLD_DIR: &str = "world";
+
 #[derive(Component)]
 pub struct Grid {
-    pub chunks: Vec<Arc<RwLock<Chunk>>>,
-    pub grid_width: usize,
-    pub grid_height: usize,
+    /// Loaded chunks, keyed by their coordinate in chunk space. Chunks
+    /// outside the streamed radius simply aren't present as keys, so the
+    /// world is effectively unbounded instead of sized to the window.
+    pub chunks: HashMap<CPos, Arc<RwLock<Chunk>>>,
+    /// The `SpriteBundle` entity spawned for each loaded chunk, so a chunk
+    /// streaming out can despawn its sprite instead of leaking the entity.
+    pub chunk_entities: HashMap<CPos, Entity>,
     pub dt: f32,
 }
 
-fn grid_setup(mut commands: Commands, windows: Query<&Window>, mut images: ResMut<Assets<Image>>) {
-    let window = windows.single();
+fn chunk_path(cpos: CPos) -> std::path::PathBuf {
+    std::path::Path::new(WORLD_DIR).join(format!("{}_{}.chunk", cpos.x, cpos.y))
+}
+
+/// Spawns the sprite for a chunk at `cpos` and either loads its saved state
+/// from disk or creates a fresh, empty chunk.
+fn spawn_chunk(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    cpos: CPos,
+) -> (Entity, Arc<RwLock<Chunk>>) {
     let side_length = (CHUNK_SIZE * ATOM_SIZE) as f32;
+    let pos = Vec2::new(cpos.x as f32 * side_length, -(cpos.y as f32) * side_length);
+
+    let texture = images.add(Chunk::new_image());
+    let entity = commands
+        .spawn(SpriteBundle {
+            texture: texture.clone(),
+            sprite: Sprite {
+                anchor: sprite::Anchor::TopLeft,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(pos.x, pos.y, 0.),
+            ..Default::default()
+        })
+        .id();
+
+    let mut chunk = fs::read(chunk_path(cpos))
+        .ok()
+        .map(|bytes| Chunk::from_rle_bytes(&bytes, texture.clone()))
+        .unwrap_or_else(|| Chunk::new(texture));
+
+    let image = images.get_mut(&chunk.texture).unwrap();
+    chunk.update_all(image);
 
-    let (mut grid_width, mut grid_height) = (
-        (window.width() / side_length).ceil() as usize,
-        (window.height() / side_length).ceil() as usize,
+    (entity, Arc::new(RwLock::new(chunk)))
+}
+
+/// Streams chunks in and out based on the camera's position: chunks entering
+/// `STREAM_RADIUS` are loaded (from disk if they were saved before), chunks
+/// leaving it are serialized to disk and dropped from the grid.
+fn stream_chunks(
+    mut commands: Commands,
+    mut grid: Query<&mut Grid>,
+    camera: Query<&Transform, With<Camera>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Ok(camera_transform) = camera.get_single() else {
+        return;
+    };
+    let mut grid = grid.single_mut();
+
+    let side_length = (CHUNK_SIZE * ATOM_SIZE) as f32;
+    let center = CPos::new(
+        (camera_transform.translation.x / side_length).floor() as i32,
+        (-camera_transform.translation.y / side_length).floor() as i32,
     );
 
-    //If chunks aren't even, make them
-    if grid_width % 2 != 0 {
-        grid_width += 1
-    }
-    if grid_height % 2 != 0 {
-        grid_height += 1
-    }
-
-    println!("{} {}", grid_width, grid_height);
-
-    let mut chunks = vec![];
-    for y in 0..grid_height {
-        for x in 0..grid_width {
-            // Get image position
-            let mut pos = Vec2::new(x as f32 * side_length, -(y as f32) * side_length);
-            pos.x -= grid_width as f32 / 2. * side_length;
-            pos.y += grid_height as f32 / 2. * side_length;
-
-            //Get and spawn texture/chunk image
-            let texture = images.add(Chunk::new_image());
-            commands.spawn(SpriteBundle {
-                texture: texture.clone(),
-                sprite: Sprite {
-                    anchor: sprite::Anchor::TopLeft,
-                    ..Default::default()
-                },
-                transform: Transform::from_xyz(pos.x, pos.y, 0.),
-                ..Default::default()
-            });
+    for y in -STREAM_RADIUS..=STREAM_RADIUS {
+        for x in -STREAM_RADIUS..=STREAM_RADIUS {
+            let cpos = center + CPos::new(x, y);
+            if grid.chunks.contains_key(&cpos) {
+                continue;
+            }
+
+            let (entity, chunk) = spawn_chunk(&mut commands, &mut images, cpos);
+            grid.chunks.insert(cpos, chunk);
+            grid.chunk_entities.insert(cpos, entity);
+        }
+    }
+
+    let to_unload: Vec<CPos> = grid
+        .chunks
+        .keys()
+        .filter(|cpos| {
+            (cpos.x - center.x).abs() > STREAM_RADIUS || (cpos.y - center.y).abs() > STREAM_RADIUS
+        })
+        .copied()
+        .collect();
+
+    for cpos in to_unload {
+        let Some(chunk) = grid.chunks.remove(&cpos) else {
+            continue;
+        };
+        let chunk = chunk.read().unwrap();
 
-            //Create chunk
-            let chunk = Chunk::new(texture);
+        if fs::create_dir_all(WORLD_DIR)
+            .and_then(|_| fs::write(chunk_path(cpos), chunk.to_rle_bytes()))
+            .is_err()
+        {
+            error!("Failed to save chunk {cpos} to disk, its changes will be lost");
+        }
 
-            //Update chunk image
-            let image = images.get_mut(&chunk.texture).unwrap();
-            chunk.update_all(image);
+        images.remove(chunk.texture.clone());
 
-            chunks.push(Arc::new(RwLock::new(chunk)));
+        if let Some(entity) = grid.chunk_entities.remove(&cpos) {
+            commands.entity(entity).despawn();
         }
     }
+}
+
+#[derive(Resource)]
+pub struct GridWorkerPool(WorkerPool);
+
+fn worker_pool_setup(mut commands: Commands) {
+    commands.insert_resource(GridWorkerPool(WorkerPool::new(WORKER_COUNT)));
+}
+
+/// Seeds the grid with the chunks around the origin; `stream_chunks` takes
+/// over from here as the camera moves.
+fn grid_setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let mut chunks = HashMap::new();
+    let mut chunk_entities = HashMap::new();
 
-    let grid = Grid {
+    for y in -STREAM_RADIUS..=STREAM_RADIUS {
+        for x in -STREAM_RADIUS..=STREAM_RADIUS {
+            let cpos = CPos::new(x, y);
+            let (entity, chunk) = spawn_chunk(&mut commands, &mut images, cpos);
+            chunks.insert(cpos, chunk);
+            chunk_entities.insert(cpos, entity);
+        }
+    }
+
+    commands.spawn(Grid {
         chunks,
-        grid_width,
-        grid_height,
+        chunk_entities,
         dt: 0.,
-    };
-    commands.spawn(grid);
+    });
 }
 
-pub fn grid_update(mut grid: Query<&mut Grid>, mut images: ResMut<Assets<Image>>, time: Res<Time>) {
+pub fn grid_update(
+    mut grid: Query<&mut Grid>,
+    mut pool: ResMut<GridWorkerPool>,
+    mut images: ResMut<Assets<Image>>,
+    time: Res<Time>,
+    registry: Res<MaterialRegistryHandle>,
+    mut dirty: ResMut<LightDirty>,
+) {
+    let pool = &mut pool.0;
     let mut grid = grid.single_mut();
+    let registry = Arc::clone(&registry.0);
 
     grid.dt += time.delta_seconds();
     let dt = grid.dt;
@@ -94,87 +335,110 @@ pub fn grid_update(mut grid: Query<&mut Grid>, mut images: ResMut<Assets<Image>>
         return;
     }
 
-    let row_range = 0..grid.grid_width as i32;
-    let column_range = 0..grid.grid_height as i32;
-
-    // Get images
-    let images_removed: Vec<(Handle<Image>, Arc<Mutex<Image>>)> = grid
+    // Get images, keyed by the same chunk coordinate as `grid.chunks` so
+    // neighbours can be resolved through the map below.
+    let images_removed: HashMap<CPos, Arc<Mutex<Image>>> = grid
         .chunks
         .iter()
-        .map(|chunk| {
+        .map(|(&cpos, chunk)| {
+            let texture = chunk.read().unwrap().texture.clone();
             (
-                chunk.read().unwrap().texture.clone(),
-                Arc::new(Mutex::new(
-                    images
-                        .remove(chunk.read().unwrap().texture.clone())
-                        .unwrap(),
-                )),
+                cpos,
+                Arc::new(Mutex::new(images.remove(texture).unwrap())),
             )
         })
         .collect();
 
-    let update_vec: Vec<bool> = grid
+    let update_map: HashMap<CPos, bool> = grid
         .chunks
         .iter()
-        .map(|chunk| chunk.read().unwrap().active)
+        .map(|(&cpos, chunk)| (cpos, chunk.read().unwrap().active))
         .collect();
 
-    for chunk in &grid.chunks {
+    for chunk in grid.chunks.values() {
         chunk.write().unwrap().active = false;
     }
 
-    // Run the 4 update steps in checker like pattern
+    // Run the 4 update steps in checker like pattern, dispatching each
+    // chunk's work to the persistent worker pool instead of spawning a
+    // thread per chunk.
     for y_thread_off in rand_range(0..2) {
         for x_thread_off in rand_range(0..2) {
-            let mut handles = vec![];
-
             //Acess chunks
-            for y in (y_thread_off..grid.grid_height).step_by(2) {
-                for x in (x_thread_off..grid.grid_width).step_by(2) {
-                    if !update_vec[y * grid.grid_width + x] {
-                        continue;
-                    }
+            for (&cpos, chunk) in &grid.chunks {
+                if cpos.x.rem_euclid(2) != x_thread_off as i32
+                    || cpos.y.rem_euclid(2) != y_thread_off as i32
+                {
+                    continue;
+                }
+                if !update_map.get(&cpos).copied().unwrap_or(false) {
+                    continue;
+                }
 
-                    let mut chunks = vec![];
-                    // Get all 3x3 chunks for each chunk updating
-                    for y_off in -1..=1 {
-                        for x_off in -1..=1 {
-                            if !column_range.contains(&(y as i32 + y_off))
-                                || !row_range.contains(&(x as i32 + x_off))
-                            {
-                                chunks.push(None);
-                                continue;
-                            }
+                // Block until a worker is actually idle instead of round-
+                // robining blindly, so a phase with more chunks than
+                // `WORKER_COUNT` still only ever reuses reclaimed scratch
+                // buffers rather than allocating fresh ones.
+                let worker_idx = pool.acquire_idle();
+
+                // Reuse the worker's scratch buffer instead of allocating a
+                // fresh `chunks` Vec every tick.
+                let mut chunks = pool.take_scratch(worker_idx);
 
-                            let index = ((y as i32 + y_off) * grid.grid_width as i32
-                                + x as i32
-                                + x_off) as usize;
+                // Get all 3x3 chunks for each chunk updating, resolving
+                // neighbours through the map and treating missing (not
+                // streamed in) chunks as `None`.
+                for y_off in -1..=1 {
+                    for x_off in -1..=1 {
+                        let neighbour_pos = cpos + CPos::new(x_off, y_off);
 
-                            chunks.push(Some((
-                                Arc::clone(&grid.chunks[index]),
-                                Arc::clone(&images_removed[index].1),
-                            )));
+                        let neighbour = if neighbour_pos == cpos {
+                            Some(chunk)
+                        } else {
+                            grid.chunks.get(&neighbour_pos)
+                        };
+
+                        match neighbour.zip(images_removed.get(&neighbour_pos)) {
+                            Some((neighbour, image)) => {
+                                chunks.push(Some((Arc::clone(neighbour), Arc::clone(image))))
+                            }
+                            None => chunks.push(None),
                         }
                     }
-
-                    let handle = thread::spawn(move || update_chunks(chunks, dt));
-                    handles.push(handle);
                 }
-            }
 
-            // Wait for update step to finish
-            for handle in handles {
-                handle.join().unwrap()
+                pool.dispatch(worker_idx, chunks, dt, Arc::clone(&registry));
             }
+
+            // Wait for this phase's dispatched work to finish before moving
+            // to the next offset, preserving the checkerboard data-race
+            // guarantees.
+            pool.wait_all();
         }
     }
 
     // Add images back after update
-    for image in images_removed {
-        images.set_untracked(
-            image.0,
-            Arc::try_unwrap(image.1).unwrap().into_inner().unwrap(),
-        )
+    for (cpos, image) in images_removed {
+        let texture = grid.chunks[&cpos].read().unwrap().texture.clone();
+        images.set_untracked(texture, Arc::try_unwrap(image).unwrap().into_inner().unwrap());
+    }
+
+    // Mark every atom of every chunk that actually ran this tick as
+    // light-dirty too, not just atoms whose `light` literally changed, so
+    // `drain_dirty_textures` is the one place a pixel's final color gets
+    // written - material/state changes from the simulation above go
+    // through the same `blend_pixel` pass as light instead of racing a
+    // separate unlit-color renderer and flickering.
+    for (&cpos, &was_active) in &update_map {
+        if !was_active {
+            continue;
+        }
+        let positions = dirty.0.entry(cpos).or_default();
+        for y in 0..CHUNK_SIZE as i32 {
+            for x in 0..CHUNK_SIZE as i32 {
+                positions.insert(IVec2::new(x, y));
+            }
+        }
     }
 
     grid.dt -= UPDATE_TIME;
@@ -186,15 +450,21 @@ fn rand_range(vec: Range<usize>) -> Vec<usize> {
     vec
 }
 
-pub fn update_chunks(chunks: UpdateChunksType, dt: f32) {
+// Relies on `Atom` carrying a `material: MaterialId` field (replacing
+// hardcoded per-state behaviour with a lookup into `MaterialRegistry`) and on
+// `State` carrying a `Gas` variant alongside `Void`/`Powder`/`Liquid`.
+
+pub fn update_chunks(chunks: &UpdateChunksType, dt: f32, registry: &MaterialRegistry) {
     for y in rand_range(CHUNK_SIZE - 1..CHUNK_SIZE * 2 + 1) {
         for x in rand_range(CHUNK_SIZE - 1..CHUNK_SIZE * 2 + 1) {
             let pos = ivec2(x as i32, y as i32);
 
-            if !dt_upable(&chunks, pos, dt) {
+            if !dt_upable(chunks, pos, dt) {
                 continue;
             }
 
+            try_react(chunks, pos, dt, registry);
+
             let state;
             {
                 let local_pos = global_to_local(pos);
@@ -209,15 +479,125 @@ pub fn update_chunks(chunks: UpdateChunksType, dt: f32) {
             }
 
             match state {
-                State::Powder => update_powder(&chunks, pos, dt),
-                State::Liquid => update_liquid(&chunks, pos, dt),
+                State::Powder => update_powder(chunks, pos, dt, registry),
+                State::Liquid => update_liquid(chunks, pos, dt, registry),
+                State::Gas => update_gas(chunks, pos, dt, registry),
                 _ => (),
             }
         }
     }
 }
 
-fn update_powder(chunks: &UpdateChunksType, pos: IVec2, dt: f32) {
+/// Reads the `MaterialId` carried by the atom at `pos`.
+fn atom_material(chunks: &UpdateChunksType, pos: IVec2) -> Option<MaterialId> {
+    let local_pos = global_to_local(pos);
+    Some(
+        chunks[local_pos.1 as usize]
+            .clone()?
+            .0
+            .read()
+            .unwrap()
+            .atoms[local_pos.0.d1()]
+        .material,
+    )
+}
+
+/// Overwrites the material (and derived `state`/`emission`) of the atom at
+/// `pos`.
+fn set_material(chunks: &UpdateChunksType, pos: IVec2, material: MaterialId, registry: &MaterialRegistry) {
+    let local_pos = global_to_local(pos);
+    let Some(chunk) = chunks[local_pos.1 as usize].clone() else {
+        return;
+    };
+    let mut chunk = chunk.0.write().unwrap();
+    let atom = &mut chunk.atoms[local_pos.0.d1()];
+    atom.material = material;
+    atom.state = registry.get(material).state;
+    atom.emission = registry.emission(material);
+}
+
+/// Whether the atom at `pos` (of density `density`) would sink through
+/// whatever currently occupies `target`: a void, or a liquid/gas that's
+/// lighter than it - generalizing the old Void-only downward swap check so
+/// heavier liquids sink through lighter ones instead of only falling into
+/// empty space.
+fn sinks_into(chunks: &UpdateChunksType, target: IVec2, density: f32, registry: &MaterialRegistry) -> bool {
+    match get_state(chunks, target) {
+        Some(State::Void) => true,
+        Some(State::Liquid) => atom_material(chunks, target)
+            .map(|m| registry.density(m) < density)
+            .unwrap_or(false),
+        Some(State::Gas) => atom_material(chunks, target)
+            .map(|m| registry.density(m) < density)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Whether the gas at `pos` (of density `density`) would rise through
+/// whatever currently occupies `target`: a void, or a denser gas, so lighter
+/// gases end up above heavier ones.
+fn rises_into(chunks: &UpdateChunksType, target: IVec2, density: f32, registry: &MaterialRegistry) -> bool {
+    match get_state(chunks, target) {
+        Some(State::Void) => true,
+        Some(State::Gas) => atom_material(chunks, target)
+            .map(|m| registry.density(m) > density)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Rolls each of this atom's reaction rules against its four neighbours,
+/// applying the first one that fires.
+fn try_react(chunks: &UpdateChunksType, pos: IVec2, dt: f32, registry: &MaterialRegistry) {
+    let Some(material) = atom_material(chunks, pos) else {
+        return;
+    };
+    let reactions = &registry.get(material).reactions;
+    if reactions.is_empty() {
+        return;
+    }
+
+    for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+        let neighbour_pos = pos + offset;
+        let Some(neighbour_material) = atom_material(chunks, neighbour_pos) else {
+            continue;
+        };
+
+        for rule in reactions {
+            if rule.with != neighbour_material {
+                continue;
+            }
+            if rand::thread_rng().gen_range(0.0..1.0) >= rule.probability {
+                continue;
+            }
+
+            if let Some(output) = rule.output_self {
+                set_material(chunks, pos, output, registry);
+            }
+            if let Some(output) = rule.output_other {
+                set_material(chunks, neighbour_pos, output, registry);
+            }
+
+            let local_pos = global_to_local(pos);
+            chunks[local_pos.1 as usize]
+                .clone()
+                .unwrap()
+                .0
+                .write()
+                .unwrap()
+                .atoms[local_pos.0.d1()]
+            .updated_at = dt;
+            return;
+        }
+    }
+}
+
+fn update_powder(chunks: &UpdateChunksType, pos: IVec2, dt: f32, registry: &MaterialRegistry) {
+    let density = atom_material(chunks, pos)
+        .map(|m| registry.density(m))
+        .unwrap_or(0.);
+
     let fvel = get_fvel(chunks, pos);
     let fvel = cmp::min(
         fvel + (GRAVITY as f32 * rand::thread_rng().gen_range(0.5..=1.)) as u8,
@@ -225,7 +605,7 @@ fn update_powder(chunks: &UpdateChunksType, pos: IVec2, dt: f32) {
     );
 
     for i in 1..=fvel {
-        let down = get_state(chunks, pos + IVec2::Y * i as i32) == Some(State::Void);
+        let down = sinks_into(chunks, pos + IVec2::Y * i as i32, density, registry);
 
         if !down && i == 1 {
             break;
@@ -290,13 +670,13 @@ fn update_powder(chunks: &UpdateChunksType, pos: IVec2, dt: f32) {
 
     let mut downsides = vec![
         (
-            get_state(chunks, pos + IVec2::Y + IVec2::NEG_X) == Some(State::Void)
-                && get_state(chunks, pos + IVec2::NEG_X) == Some(State::Void),
+            sinks_into(chunks, pos + IVec2::Y + IVec2::NEG_X, density, registry)
+                && sinks_into(chunks, pos + IVec2::NEG_X, density, registry),
             IVec2::Y + IVec2::NEG_X,
         ),
         (
-            get_state(chunks, pos + IVec2::Y + IVec2::X) == Some(State::Void)
-                && get_state(chunks, pos + IVec2::X) == Some(State::Void),
+            sinks_into(chunks, pos + IVec2::Y + IVec2::X, density, registry)
+                && sinks_into(chunks, pos + IVec2::X, density, registry),
             IVec2::Y + IVec2::X,
         ),
     ];
@@ -320,8 +700,12 @@ fn update_powder(chunks: &UpdateChunksType, pos: IVec2, dt: f32) {
     .updated_at = dt;
 }
 
-fn update_liquid(chunks: &UpdateChunksType, pos: IVec2, dt: f32) {
-    let down = get_state(chunks, pos + IVec2::Y) == Some(State::Void);
+fn update_liquid(chunks: &UpdateChunksType, pos: IVec2, dt: f32, registry: &MaterialRegistry) {
+    let density = atom_material(chunks, pos)
+        .map(|m| registry.density(m))
+        .unwrap_or(0.);
+
+    let down = sinks_into(chunks, pos + IVec2::Y, density, registry);
     if down {
         swap_global(chunks, pos, pos + IVec2::Y, dt);
         return;
@@ -329,23 +713,23 @@ fn update_liquid(chunks: &UpdateChunksType, pos: IVec2, dt: f32) {
 
     let mut sides = vec![
         (
-            get_state(chunks, pos + IVec2::NEG_X) == Some(State::Void),
+            sinks_into(chunks, pos + IVec2::NEG_X, density, registry),
             IVec2::NEG_X,
         ),
         (
-            get_state(chunks, pos + IVec2::X) == Some(State::Void),
+            sinks_into(chunks, pos + IVec2::X, density, registry),
             IVec2::X,
         ),
     ];
 
     let mut downsides = vec![
         (
-            get_state(chunks, pos + IVec2::Y + IVec2::NEG_X) == Some(State::Void),
+            sinks_into(chunks, pos + IVec2::Y + IVec2::NEG_X, density, registry),
             IVec2::Y + IVec2::NEG_X,
             sides[0].0,
         ),
         (
-            get_state(chunks, pos + IVec2::Y + IVec2::X) == Some(State::Void),
+            sinks_into(chunks, pos + IVec2::Y + IVec2::X, density, registry),
             IVec2::Y + IVec2::X,
             sides[1].0,
         ),
@@ -378,9 +762,420 @@ fn update_liquid(chunks: &UpdateChunksType, pos: IVec2, dt: f32) {
     .updated_at = dt;
 }
 
+/// Mirrors [`update_liquid`] with gravity flipped: gas rises into a void or a
+/// denser gas above it instead of sinking into anything less dense below.
+/// Liquids and powders above it are never swapped with.
+fn update_gas(chunks: &UpdateChunksType, pos: IVec2, dt: f32, registry: &MaterialRegistry) {
+    let density = atom_material(chunks, pos)
+        .map(|m| registry.density(m))
+        .unwrap_or(0.);
+
+    let up = rises_into(chunks, pos + IVec2::NEG_Y, density, registry);
+    if up {
+        swap_global(chunks, pos, pos + IVec2::NEG_Y, dt);
+        return;
+    }
+
+    let mut sides = vec![
+        (
+            rises_into(chunks, pos + IVec2::NEG_X, density, registry),
+            IVec2::NEG_X,
+        ),
+        (
+            rises_into(chunks, pos + IVec2::X, density, registry),
+            IVec2::X,
+        ),
+    ];
+
+    let mut upsides = vec![
+        (
+            rises_into(chunks, pos + IVec2::NEG_Y + IVec2::NEG_X, density, registry),
+            IVec2::NEG_Y + IVec2::NEG_X,
+            sides[0].0,
+        ),
+        (
+            rises_into(chunks, pos + IVec2::NEG_Y + IVec2::X, density, registry),
+            IVec2::NEG_Y + IVec2::X,
+            sides[1].0,
+        ),
+    ];
+
+    upsides.shuffle(&mut thread_rng());
+    for upside in upsides {
+        if upside.0 && upside.2 {
+            swap_global(chunks, pos, pos + upside.1, dt);
+            return;
+        }
+    }
+
+    sides.shuffle(&mut thread_rng());
+    for side in sides {
+        if side.0 {
+            swap_global(chunks, pos, pos + side.1, dt);
+            return;
+        }
+    }
+
+    let local_pos = global_to_local(pos);
+    chunks[local_pos.1 as usize]
+        .clone()
+        .unwrap()
+        .0
+        .write()
+        .unwrap()
+        .atoms[local_pos.0.d1()]
+    .updated_at = dt;
+}
+
+/// Whether two atoms are equal for RLE run-merging purposes: everything but
+/// `updated_at` - that field is a per-tick timestamp, so two otherwise
+/// identical atoms (e.g. a previously-simulated patch of `Void`) would
+/// otherwise never merge into a run just because they were last touched on
+/// different ticks, defeating the compression this encoding is built for.
+fn rle_eq(a: &Atom, b: &Atom) -> bool {
+    Atom { updated_at: 0., ..*a } == Atom { updated_at: 0., ..*b }
+}
+
+impl Chunk {
+    /// Run-length-encodes `atoms` into a compact byte buffer. Falling-sand
+    /// chunks have large uniform regions (mostly `Void`), so storing each
+    /// run as a repeat count plus one atom shrinks them drastically compared
+    /// to one entry per atom.
+    pub fn to_rle_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut atoms = self.atoms.iter();
+
+        let Some(mut current) = atoms.next() else {
+            return bytes;
+        };
+        let mut run: u32 = 1;
+
+        for atom in atoms {
+            if rle_eq(atom, current) {
+                run += 1;
+            } else {
+                bytes.extend_from_slice(&run.to_le_bytes());
+                bytes.extend_from_slice(bytemuck::bytes_of(current));
+                current = atom;
+                run = 1;
+            }
+        }
+        bytes.extend_from_slice(&run.to_le_bytes());
+        bytes.extend_from_slice(bytemuck::bytes_of(current));
+
+        bytes
+    }
+
+    /// Reconstructs a chunk from the buffer written by [`Chunk::to_rle_bytes`].
+    pub fn from_rle_bytes(bytes: &[u8], texture: Handle<Image>) -> Self {
+        let atom_size = std::mem::size_of::<Atom>();
+        let mut atoms = Vec::with_capacity(CHUNK_LEN);
+
+        let mut cursor = 0;
+        while cursor + 4 + atom_size <= bytes.len() {
+            let run = u32::from_le_bytes(bytes[cursor..cursor + 4].try_into().unwrap());
+            cursor += 4;
+
+            let atom: Atom = *bytemuck::from_bytes(&bytes[cursor..cursor + atom_size]);
+            cursor += atom_size;
+
+            atoms.extend(std::iter::repeat(atom).take(run as usize));
+        }
+        atoms.resize(CHUNK_LEN, Atom::default());
+
+        Chunk {
+            atoms: atoms.try_into().unwrap_or_else(|_| panic!("corrupt chunk save file")),
+            texture,
+            ..Default::default()
+        }
+    }
+}
+
+// Relies on `Atom` carrying two new fields: `light: u8` (0-15, this atom's
+// current light level) and `emission: u8` (0 if not a light source, else the
+// level it constantly re-seeds itself to - lava, fire, and the like).
+
+/// Highest light level an atom can carry.
+const MAX_LIGHT: u8 = 15;
+
+/// Floor on rendered brightness so an atom with `light == 0` is still drawn
+/// at a fifth of its material's color instead of going pure black once its
+/// last light source is removed.
+const AMBIENT_BRIGHTNESS: f32 = 0.2;
+
+/// How many BFS queue entries get processed per tick. A light change bigger
+/// than this (e.g. draining a big lava lake) keeps propagating over several
+/// frames instead of stalling one.
+const LIGHT_WORK_PER_TICK: usize = 4096;
+
+const LIGHT_NEIGHBOURS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+/// Light propagation work, carried across ticks so a change touching more
+/// than `LIGHT_WORK_PER_TICK` atoms resumes next frame instead of stalling.
+#[derive(Resource, Default)]
+pub struct LightQueues {
+    increase: VecDeque<IVec2>,
+    decrease: VecDeque<(IVec2, u8)>,
+    emissive_last_tick: HashSet<IVec2>,
+}
+
+/// Atom positions that need their pixel re-blended through `blend_pixel`,
+/// grouped by chunk. Populated both by the light subsystem below (a
+/// position's `light` changed) and by `grid_update` (every atom of a chunk
+/// that actually simulated this tick), so this is the single dirty set any
+/// atom mutation feeds into rather than racing a separate unlit-color
+/// redraw. Drained each tick by `drain_dirty_textures`.
+#[derive(Resource, Default)]
+pub struct LightDirty(pub HashMap<CPos, HashSet<IVec2>>);
+
+fn mark_light_dirty(dirty: &mut LightDirty, cpos: CPos, local: IVec2) {
+    dirty.0.entry(cpos).or_default().insert(local);
+}
+
+/// Splits a global atom position into the chunk it falls in and its local
+/// position within that chunk.
+fn light_chunk_and_local(pos: IVec2) -> (CPos, IVec2) {
+    let chunk_lenght = CHUNK_LENGHT as i32;
+    (
+        CPos::new(
+            pos.x.div_euclid(chunk_lenght),
+            pos.y.div_euclid(chunk_lenght),
+        ),
+        IVec2::new(
+            pos.x.rem_euclid(chunk_lenght),
+            pos.y.rem_euclid(chunk_lenght),
+        ),
+    )
+}
+
+fn light_get(grid: &Grid, pos: IVec2) -> Option<Atom> {
+    let (cpos, local) = light_chunk_and_local(pos);
+    let chunk = grid.chunks.get(&cpos)?;
+    Some(chunk.read().unwrap().atoms[local.d1()])
+}
+
+fn light_set(grid: &Grid, pos: IVec2, light: u8, dirty: &mut LightDirty) {
+    let (cpos, local) = light_chunk_and_local(pos);
+    let Some(chunk) = grid.chunks.get(&cpos) else {
+        return;
+    };
+    chunk.write().unwrap().atoms[local.d1()].light = light;
+    mark_light_dirty(dirty, cpos, local);
+}
+
+/// How much an atom's material dims light passing through it: 0 for
+/// Void/gas, higher for denser powders/solids so light dims faster through
+/// material.
+fn opacity(state: State) -> u8 {
+    match state {
+        State::Void => 0,
+        State::Gas => 0,
+        State::Liquid => 1,
+        State::Powder => 2,
+        _ => 3,
+    }
+}
+
+/// Each tick, resets every emissive atom (lava, fire, ...) that is new or no
+/// longer at its source light level back to that level and seeds the
+/// increase queue with it; sources already settled at their level are
+/// skipped. Emissive atoms that
+/// disappeared or got covered since last tick seed the decrease queue with
+/// their old level instead, so their light gets cleared (or re-propagated
+/// from still-reachable sources).
+fn seed_light_sources(
+    grid: Query<&Grid>,
+    mut queues: ResMut<LightQueues>,
+    mut dirty: ResMut<LightDirty>,
+) {
+    let Ok(grid) = grid.get_single() else {
+        return;
+    };
+
+    let mut emissive_this_tick = HashMap::new();
+    for (&cpos, chunk) in &grid.chunks {
+        let chunk = chunk.read().unwrap();
+        for (i, atom) in chunk.atoms.iter().enumerate() {
+            if atom.emission == 0 {
+                continue;
+            }
+            let local = IVec2::new((i % CHUNK_SIZE) as i32, (i / CHUNK_SIZE) as i32);
+            let pos = cpos * CHUNK_SIZE as i32 + local;
+            emissive_this_tick.insert(pos, atom.emission);
+        }
+    }
+
+    for (&pos, &level) in &emissive_this_tick {
+        let level = level.min(MAX_LIGHT);
+
+        // Only re-seed sources that are new this tick or whose source level
+        // actually changed - re-pushing every already-settled emissive atom
+        // unconditionally would keep refilling the increase queue faster
+        // than `propagate_light`'s `LIGHT_WORK_PER_TICK` budget can drain
+        // it, so the queue would only ever grow.
+        let already_seeded = queues.emissive_last_tick.contains(&pos)
+            && light_get(grid, pos).map(|atom| atom.light) == Some(level);
+        if already_seeded {
+            continue;
+        }
+
+        light_set(grid, pos, level, &mut dirty);
+        queues.increase.push_back(pos);
+    }
+
+    for pos in queues.emissive_last_tick.clone() {
+        if emissive_this_tick.contains_key(&pos) {
+            continue;
+        }
+        if let Some(atom) = light_get(grid, pos) {
+            queues.decrease.push_back((pos, atom.light));
+        }
+    }
+
+    queues.emissive_last_tick = emissive_this_tick.into_keys().collect();
+}
+
+/// Drains the decrease and increase light queues, bounded to
+/// `LIGHT_WORK_PER_TICK` total positions so a large light change spreads
+/// over several frames instead of stalling one.
+fn propagate_light(
+    grid: Query<&Grid>,
+    mut queues: ResMut<LightQueues>,
+    mut dirty: ResMut<LightDirty>,
+) {
+    let Ok(grid) = grid.get_single() else {
+        return;
+    };
+
+    let mut budget = LIGHT_WORK_PER_TICK;
+
+    // Decrease pass: clear anything that was only lit by the removed
+    // source, re-propagating from any neighbour bright enough to still
+    // reach this position from elsewhere.
+    while budget > 0 {
+        let Some((pos, old_level)) = queues.decrease.pop_front() else {
+            break;
+        };
+        budget -= 1;
+
+        // The position itself was only ever lit by the source that just
+        // disappeared - clear it here too, not just the neighbours below,
+        // or it keeps showing stale light forever.
+        light_set(grid, pos, 0, &mut dirty);
+
+        for offset in LIGHT_NEIGHBOURS {
+            let neighbour_pos = pos + offset;
+            let Some(neighbour) = light_get(grid, neighbour_pos) else {
+                continue;
+            };
+
+            if neighbour.light != 0 && neighbour.light < old_level {
+                light_set(grid, neighbour_pos, 0, &mut dirty);
+                queues.decrease.push_back((neighbour_pos, neighbour.light));
+            } else if neighbour.light >= old_level {
+                queues.increase.push_back(neighbour_pos);
+            }
+        }
+    }
+
+    // Increase pass: flood outward from every seeded/re-seeded position,
+    // dimming by 1 plus the neighbour's opacity per step.
+    while budget > 0 {
+        let Some(pos) = queues.increase.pop_front() else {
+            break;
+        };
+        budget -= 1;
+
+        let Some(current) = light_get(grid, pos) else {
+            continue;
+        };
+
+        for offset in LIGHT_NEIGHBOURS {
+            let neighbour_pos = pos + offset;
+            let Some(neighbour) = light_get(grid, neighbour_pos) else {
+                continue;
+            };
+
+            let neighbour_opacity = opacity(neighbour.state);
+            if neighbour.light + 1 + neighbour_opacity < current.light {
+                light_set(
+                    grid,
+                    neighbour_pos,
+                    current.light - 1 - neighbour_opacity,
+                    &mut dirty,
+                );
+                queues.increase.push_back(neighbour_pos);
+            }
+        }
+    }
+}
+
+/// Converts a material's base color and an atom's light level into the
+/// RGBA8 pixel that should be written to its chunk's texture.
+fn blend_pixel(color: Color, light: u8) -> [u8; 4] {
+    let brightness = (light as f32 / MAX_LIGHT as f32).max(AMBIENT_BRIGHTNESS);
+    let [r, g, b, a] = color.as_rgba_f32();
+    [
+        (r * brightness * 255.).round() as u8,
+        (g * brightness * 255.).round() as u8,
+        (b * brightness * 255.).round() as u8,
+        (a * 255.).round() as u8,
+    ]
+}
+
+/// Drains the positions `seed_light_sources`/`propagate_light` (and the
+/// rigid-body systems in `physics.rs`) mark dirty, re-blending each one's
+/// material color and light level into its chunk's texture. Without this,
+/// `light` only ever changed inside `Atom` and never reached the screen.
+fn drain_dirty_textures(
+    grid: Query<&Grid>,
+    mut dirty: ResMut<LightDirty>,
+    mut images: ResMut<Assets<Image>>,
+    registry: Res<MaterialRegistryHandle>,
+) {
+    let Ok(grid) = grid.get_single() else {
+        return;
+    };
+
+    for (cpos, positions) in dirty.0.drain() {
+        let Some(chunk) = grid.chunks.get(&cpos) else {
+            continue;
+        };
+        let chunk = chunk.read().unwrap();
+        let Some(image) = images.get_mut(&chunk.texture) else {
+            continue;
+        };
+
+        for local in positions {
+            let atom = chunk.atoms[local.d1()];
+            let material = registry.get(atom.material);
+            let color = material.tint.resolve(material.color, cpos * CHUNK_SIZE as i32 + local);
+            let pixel = blend_pixel(color, atom.light);
+
+            let index = local.d1() * 4;
+            if let Some(slice) = image.data.get_mut(index..index + 4) {
+                slice.copy_from_slice(&pixel);
+            }
+        }
+    }
+}
+
 pub struct GridPlugin;
 impl Plugin for GridPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(grid_setup).add_system(grid_update);
+        app.init_resource::<LightQueues>()
+            .init_resource::<LightDirty>()
+            .init_resource::<MaterialRegistryHandle>()
+            .add_startup_system(worker_pool_setup)
+            .add_startup_system(grid_setup)
+            .add_system(grid_update)
+            .add_system(stream_chunks)
+            // `grid_update` marks every atom it simulates dirty into the
+            // same `LightDirty` set the light subsystem uses, so this chain
+            // must run after it: otherwise this tick's material/light dirty
+            // marks wouldn't exist yet for `drain_dirty_textures` to drain.
+            .add_system(seed_light_sources.before(propagate_light).after(grid_update))
+            .add_system(propagate_light)
+            .add_system(drain_dirty_textures.after(propagate_light));
     }
 }
\ No newline at end of file
@@ -8,6 +8,10 @@ use rand::Rng;
 
 use crate::prelude::*;
 
+/// Coordinate of a chunk in chunk space, used to key the grid's sparse
+/// chunk map instead of a flat, fixed-size index.
+pub type CPos = IVec2;
+
 // Parallel reference for image and chunk data
 pub type TexturesHash = HashMap<usize, HashSet<IVec2>>;
 pub type ParTexturesHash = Arc<Mutex<TexturesHash>>;
@@ -193,31 +197,29 @@ pub fn rand_range(vec: Range<usize>) -> Vec<usize> {
     vec
 }
 
-// Transform pos to chunk coords
-pub fn transform_to_chunk(pos: Vec2) -> Option<(IVec2, i32)> {
-    if pos.x < 0. || pos.y < 0. {
-        return None;
-    }
-
-    let (width, height) = (CHUNKS_WIDTH, CHUNKS_HEIGHT);
-
-    let (chunk_x, chunk_y) = (
-        (pos.x / (CHUNK_LENGHT * ATOM_SIZE) as f32) as usize,
-        (pos.y / (CHUNK_LENGHT * ATOM_SIZE) as f32) as usize,
+/// Transforms a world-space position into the local atom position within its
+/// chunk, plus the coordinate (`CPos`) of that chunk. Chunks are stored in a
+/// sparse map keyed by `CPos` rather than a fixed-size grid, so every
+/// position resolves to a chunk coordinate - whether that chunk is actually
+/// loaded is for the caller to check against the map.
+pub fn transform_to_chunk(pos: Vec2) -> (IVec2, CPos) {
+    let chunk_side = (CHUNK_LENGHT * ATOM_SIZE) as f32;
+
+    // Chunks are placed in world space with y decreasing as `CPos.y`
+    // increases (mirroring `spawn_chunk`'s `-cpos.y * side` placement and
+    // `stream_chunks`' `-camera.y` centering), so resolving a world position
+    // back to a chunk/atom position has to negate `pos.y` the same way.
+    let chunk_pos = CPos::new(
+        (pos.x / chunk_side).floor() as i32,
+        (-pos.y / chunk_side).floor() as i32,
     );
 
-    if chunk_x >= width || chunk_y >= height {
-        return None;
-    }
-
     let (atom_x, atom_y) = (
-        ((pos.x / ATOM_SIZE as f32) % CHUNK_LENGHT as f32) as i32,
-        ((pos.y / ATOM_SIZE as f32) % CHUNK_LENGHT as f32) as i32,
+        (pos.x / ATOM_SIZE as f32).rem_euclid(CHUNK_LENGHT as f32) as i32,
+        (-pos.y / ATOM_SIZE as f32).rem_euclid(CHUNK_LENGHT as f32) as i32,
     );
 
-    let local = (ivec2(atom_x, atom_y), (chunk_y * width + chunk_x) as i32);
-
-    Some(local)
+    (ivec2(atom_x, atom_y), chunk_pos)
 }
 
 pub trait D1 {
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use crate::atom::State;
+
+/// Identifies an entry in the material registry. Atoms carry one of these
+/// instead of baking density/color/reaction behaviour into `State` match
+/// arms, so adding a material is a registry entry rather than a code change.
+pub type MaterialId = u16;
+
+pub const VOID: MaterialId = 0;
+pub const WATER: MaterialId = 1;
+pub const SAND: MaterialId = 2;
+pub const STONE: MaterialId = 3;
+pub const LAVA: MaterialId = 4;
+pub const STEAM: MaterialId = 5;
+pub const FIRE: MaterialId = 6;
+
+/// A reaction this material can have with a neighbour: with probability
+/// `probability` per tick, replace this atom and/or the neighbour with
+/// `output_self`/`output_other` (`None` leaves that side untouched).
+#[derive(Clone)]
+pub struct ReactionRule {
+    pub with: MaterialId,
+    pub probability: f32,
+    pub output_self: Option<MaterialId>,
+    pub output_other: Option<MaterialId>,
+}
+
+/// How a material's base `color` is applied to an individual atom's
+/// rendered pixel.
+#[derive(Clone, Copy)]
+pub enum TintType {
+    /// Always rendered as the material's base `color`, unmodified.
+    Solid,
+    /// Jittered by up to `variance` (0-1) around the base `color`, seeded
+    /// from the atom's grid position so the same cell always dithers to
+    /// the same shade instead of flickering on every redraw. Breaks up
+    /// large uniform regions (sand, stone) with a bit of texture.
+    Randomized(f32),
+}
+
+impl TintType {
+    pub fn resolve(&self, color: Color, pos: IVec2) -> Color {
+        match *self {
+            TintType::Solid => color,
+            TintType::Randomized(variance) => {
+                let seed = pos.x.wrapping_mul(73_856_093) ^ pos.y.wrapping_mul(19_349_663);
+                let jitter = (seed as u32 % 1000) as f32 / 1000.;
+                let factor = 1. - variance / 2. + jitter * variance;
+                Color::rgba(
+                    (color.r() * factor).clamp(0., 1.),
+                    (color.g() * factor).clamp(0., 1.),
+                    (color.b() * factor).clamp(0., 1.),
+                    color.a(),
+                )
+            }
+        }
+    }
+}
+
+/// A material's data-driven behaviour.
+#[derive(Clone)]
+pub struct Material {
+    pub name: &'static str,
+    pub state: State,
+    pub density: f32,
+    /// Light level (0-15) this material constantly re-seeds itself to, same
+    /// scale as `Atom::emission`/`MAX_LIGHT` in the light subsystem. 0 means
+    /// it's not a light source.
+    pub emission: u8,
+    pub color: Color,
+    pub tint: TintType,
+    pub reactions: Vec<ReactionRule>,
+    /// Whether this material counts as structural for `physics::extract_detached_solids`
+    /// - independent of `state`, since a material can fall as loose powder
+    /// (`STONE`) and still be the thing that system is meant to carve out.
+    pub rigid: bool,
+}
+
+#[derive(Clone)]
+pub struct MaterialRegistry(pub HashMap<MaterialId, Material>);
+
+impl MaterialRegistry {
+    /// Looks up a material definition. Panics on an unknown id, the same way
+    /// indexing an atom array out of bounds would - a bogus `MaterialId` is
+    /// a bug, not a recoverable runtime state.
+    pub fn get(&self, id: MaterialId) -> &Material {
+        self.0
+            .get(&id)
+            .unwrap_or_else(|| panic!("unknown material id {id}"))
+    }
+
+    pub fn density(&self, id: MaterialId) -> f32 {
+        self.get(id).density
+    }
+
+    pub fn emission(&self, id: MaterialId) -> u8 {
+        self.get(id).emission
+    }
+}
+
+/// The actual Bevy resource: an `Arc` around the registry so systems that
+/// need to hand it to worker threads (`grid_update`) clone the handle
+/// instead of deep-cloning every `Material` (and its `Vec<ReactionRule>`)
+/// on every tick.
+#[derive(Resource, Clone)]
+pub struct MaterialRegistryHandle(pub Arc<MaterialRegistry>);
+
+impl Default for MaterialRegistryHandle {
+    fn default() -> Self {
+        Self(Arc::new(MaterialRegistry::default()))
+    }
+}
+
+impl std::ops::Deref for MaterialRegistryHandle {
+    type Target = MaterialRegistry;
+
+    fn deref(&self) -> &MaterialRegistry {
+        &self.0
+    }
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        let mut materials = HashMap::new();
+
+        materials.insert(
+            VOID,
+            Material {
+                name: "Void",
+                state: State::Void,
+                density: 0.,
+                emission: 0,
+                color: Color::NONE,
+                tint: TintType::Solid,
+                reactions: vec![],
+                rigid: false,
+            },
+        );
+        materials.insert(
+            WATER,
+            Material {
+                name: "Water",
+                state: State::Liquid,
+                density: 1.,
+                emission: 0,
+                color: Color::rgb(0.2, 0.4, 0.9),
+                tint: TintType::Solid,
+                reactions: vec![ReactionRule {
+                    with: LAVA,
+                    probability: 0.4,
+                    output_self: Some(STEAM),
+                    output_other: Some(STONE),
+                }],
+                rigid: false,
+            },
+        );
+        materials.insert(
+            SAND,
+            Material {
+                name: "Sand",
+                state: State::Powder,
+                density: 3.,
+                emission: 0,
+                color: Color::rgb(0.9, 0.8, 0.4),
+                tint: TintType::Randomized(0.15),
+                reactions: vec![],
+                rigid: false,
+            },
+        );
+        materials.insert(
+            STONE,
+            Material {
+                name: "Stone",
+                state: State::Powder,
+                density: 5.,
+                emission: 0,
+                color: Color::rgb(0.5, 0.5, 0.5),
+                tint: TintType::Randomized(0.2),
+                reactions: vec![],
+                rigid: true,
+            },
+        );
+        materials.insert(
+            LAVA,
+            Material {
+                name: "Lava",
+                state: State::Liquid,
+                density: 4.,
+                emission: 13,
+                color: Color::rgb(0.9, 0.3, 0.1),
+                tint: TintType::Randomized(0.1),
+                reactions: vec![ReactionRule {
+                    with: WATER,
+                    probability: 0.4,
+                    output_self: Some(STONE),
+                    output_other: Some(STEAM),
+                }],
+                rigid: false,
+            },
+        );
+        materials.insert(
+            STEAM,
+            Material {
+                name: "Steam",
+                state: State::Gas,
+                density: -1.,
+                emission: 0,
+                color: Color::rgba(0.8, 0.8, 0.8, 0.5),
+                tint: TintType::Solid,
+                reactions: vec![],
+                rigid: false,
+            },
+        );
+        materials.insert(
+            FIRE,
+            Material {
+                name: "Fire",
+                state: State::Gas,
+                density: -2.,
+                emission: 9,
+                color: Color::rgb(1., 0.6, 0.1),
+                tint: TintType::Randomized(0.2),
+                reactions: vec![],
+                rigid: false,
+            },
+        );
+
+        Self(materials)
+    }
+}
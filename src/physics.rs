@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::atom::Atom;
+use crate::consts::*;
+use crate::grid::Grid;
+use crate::grid_api::{CPos, D1};
+use crate::materials::{MaterialId, MaterialRegistry, MaterialRegistryHandle};
+
+/// Cells are scanned for detachable solid clusters on this interval rather
+/// than every tick - flood-filling the whole loaded world is too expensive
+/// to run every frame.
+const EXTRACTION_PERIOD: f32 = 2.;
+
+/// Components bigger than this are left alone: the point of this system is
+/// carved-off rubble and debris, not turning half the world into one body.
+const MAX_BODY_ATOMS: usize = 4096;
+
+#[derive(Resource)]
+pub struct ExtractionTimer(pub Timer);
+
+impl Default for ExtractionTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(EXTRACTION_PERIOD, TimerMode::Repeating))
+    }
+}
+
+/// An atom that was pulled out of the grid to become part of a rigid body.
+/// `local` is its position relative to the body's spawn origin in atom
+/// units, so it can be re-stamped at `origin + local` under the body's
+/// current transform each frame.
+struct BodyAtom {
+    local: IVec2,
+    atom: Atom,
+}
+
+/// A chunk of the world that broke off into a rigid body: the atoms that
+/// used to live in the grid, carried along so the falling-sand simulation
+/// keeps seeing and interacting with them while the body is awake.
+#[derive(Component)]
+pub struct RigidBodyAtoms {
+    atoms: Vec<BodyAtom>,
+    /// Atom-space position the body last stamped itself into the grid at,
+    /// so that spot can be cleared again before re-stamping at the new one.
+    last_stamp: HashSet<IVec2>,
+}
+
+/// Whether an atom of this material counts as structural for the purposes of
+/// detaching into a rigid body. Driven by the material registry (rather than
+/// `Atom::state`) so it reflects what a material *is* instead of how it
+/// currently flows - e.g. `STONE` is registered as `State::Powder` so it can
+/// still fall as loose rubble, but it's the one material this system is
+/// meant to carve out.
+fn is_solid(material: MaterialId, registry: &MaterialRegistry) -> bool {
+    registry.get(material).rigid
+}
+
+fn global_atom(cpos: CPos, local: IVec2) -> IVec2 {
+    cpos * CHUNK_SIZE as i32 + local
+}
+
+/// Converts an atom-space position (y increasing downward) to the world
+/// space chunk sprites are actually placed in (`spawn_chunk` draws chunk
+/// `cpos` at world `y = -cpos.y * side`, y increasing upward) - every
+/// atom-space/world-space conversion in this module goes through this so
+/// rigid bodies fall the same direction their atoms do.
+fn atom_to_world(pos: Vec2) -> Vec2 {
+    Vec2::new(pos.x, -pos.y) * ATOM_SIZE as f32
+}
+
+/// Inverse of [`atom_to_world`].
+fn world_to_atom(pos: Vec2) -> Vec2 {
+    Vec2::new(pos.x, -pos.y) / ATOM_SIZE as f32
+}
+
+fn get_atom(grid: &Grid, pos: IVec2) -> Option<Atom> {
+    let chunk_lenght = CHUNK_SIZE as i32;
+    let cpos = CPos::new(pos.x.div_euclid(chunk_lenght), pos.y.div_euclid(chunk_lenght));
+    let local = IVec2::new(pos.x.rem_euclid(chunk_lenght), pos.y.rem_euclid(chunk_lenght));
+
+    grid.chunks
+        .get(&cpos)
+        .map(|chunk| chunk.read().unwrap().atoms[local.d1()])
+}
+
+/// Flood-fills out from `start` over 4-connected solid atoms, returning the
+/// component and whether it touches the edge of the currently loaded world
+/// (an unloaded/missing neighbour chunk) - such a component might still be
+/// attached to more ground we just haven't streamed in, so it's left alone.
+fn flood_fill_solid(
+    grid: &Grid,
+    start: IVec2,
+    visited: &mut HashSet<IVec2>,
+    registry: &MaterialRegistry,
+) -> (Vec<IVec2>, bool) {
+    let mut component = Vec::new();
+    let mut touches_edge = false;
+    let mut queue = std::collections::VecDeque::from([start]);
+    visited.insert(start);
+
+    while let Some(pos) = queue.pop_front() {
+        component.push(pos);
+
+        for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            let neighbour_pos = pos + offset;
+            let Some(neighbour) = get_atom(grid, neighbour_pos) else {
+                touches_edge = true;
+                continue;
+            };
+
+            if !is_solid(neighbour.material, registry) || visited.contains(&neighbour_pos) {
+                continue;
+            }
+
+            visited.insert(neighbour_pos);
+            queue.push_back(neighbour_pos);
+        }
+    }
+
+    (component, touches_edge)
+}
+
+/// Periodically scans the loaded world for solid clusters that have been
+/// carved loose from everything around them, turning each one into a rigid
+/// body: a collider is built from the component's cells, the originating
+/// atoms are cleared out of the grid, and the body carries them along so
+/// the simulation keeps treating that space as solid.
+pub fn extract_detached_solids(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut timer: ResMut<ExtractionTimer>,
+    grid: Query<&Grid>,
+    mut dirty: ResMut<crate::grid::LightDirty>,
+    registry: Res<MaterialRegistryHandle>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let Ok(grid) = grid.get_single() else {
+        return;
+    };
+
+    let mut visited = HashSet::new();
+    // Spawned only after the scan, once no chunk read lock is held:
+    // `spawn_rigid_body` write-locks the chunks the component lives in.
+    let mut detached = Vec::new();
+    for (&cpos, chunk) in &grid.chunks {
+        let chunk = chunk.read().unwrap();
+        for (i, atom) in chunk.atoms.iter().enumerate() {
+            if !is_solid(atom.material, &registry) {
+                continue;
+            }
+            let local = IVec2::new((i % CHUNK_SIZE) as i32, (i / CHUNK_SIZE) as i32);
+            let pos = global_atom(cpos, local);
+
+            if visited.contains(&pos) {
+                continue;
+            }
+
+            let (component, touches_edge) = flood_fill_solid(grid, pos, &mut visited, &registry);
+            if touches_edge || component.len() > MAX_BODY_ATOMS {
+                continue;
+            }
+
+            detached.push(component);
+        }
+    }
+
+    for component in detached {
+        spawn_rigid_body(&mut commands, grid, &component, &mut dirty);
+    }
+}
+
+fn spawn_rigid_body(
+    commands: &mut Commands,
+    grid: &Grid,
+    component: &[IVec2],
+    dirty: &mut crate::grid::LightDirty,
+) {
+    if component.len() < 3 {
+        return;
+    }
+
+    // Use the component's bounding-box min as the body's origin so atom
+    // offsets stay small and positive.
+    let origin = IVec2::new(
+        component.iter().map(|p| p.x).min().unwrap(),
+        component.iter().map(|p| p.y).min().unwrap(),
+    );
+
+    // `Collider::convex_hull` only keeps the outer points of whatever it's
+    // given, so handing it every cell directly is exactly as correct as (and
+    // far cheaper than) tracing a precise concave outline first and
+    // simplifying it down - that pass was being thrown away by the hull
+    // regardless. Checked before the grid is mutated below, so a degenerate
+    // component leaves the atoms in place instead of carving a hole with
+    // nothing to carry them.
+    let collider_points: Vec<Vec2> = component
+        .iter()
+        .map(|&p| atom_to_world(p.as_vec2() - origin.as_vec2()))
+        .collect();
+
+    let Some(collider) = Collider::convex_hull(&collider_points) else {
+        return;
+    };
+
+    let atoms: Vec<BodyAtom> = component
+        .iter()
+        .filter_map(|&pos| {
+            get_atom(grid, pos).map(|atom| BodyAtom {
+                local: pos - origin,
+                atom,
+            })
+        })
+        .collect();
+
+    // Clear the originating atoms out of the grid and mark them dirty so
+    // the renderer stops drawing them from the static chunk image.
+    for &pos in component {
+        let chunk_lenght = CHUNK_SIZE as i32;
+        let cpos = CPos::new(pos.x.div_euclid(chunk_lenght), pos.y.div_euclid(chunk_lenght));
+        let local = IVec2::new(pos.x.rem_euclid(chunk_lenght), pos.y.rem_euclid(chunk_lenght));
+
+        if let Some(chunk) = grid.chunks.get(&cpos) {
+            chunk.write().unwrap().atoms[local.d1()] = Atom::default();
+            dirty.0.entry(cpos).or_default().insert(local);
+        }
+    }
+
+    let world_origin = atom_to_world(origin.as_vec2());
+
+    commands.spawn((
+        RigidBody::Dynamic,
+        collider,
+        Velocity::default(),
+        Sleeping::default(),
+        TransformBundle::from_transform(Transform::from_xyz(world_origin.x, world_origin.y, 0.)),
+        RigidBodyAtoms {
+            atoms,
+            last_stamp: HashSet::new(),
+        },
+    ));
+}
+
+/// Rotates a body-local atom offset (in atom units) by the body's current
+/// transform and maps it back onto the atom grid, so a tumbling body stamps
+/// its actual rotated footprint instead of always its original axis-aligned
+/// one.
+fn stamped_pos(transform: &Transform, local: IVec2) -> IVec2 {
+    let local_offset = atom_to_world(local.as_vec2()).extend(0.);
+    let world_offset = (transform.rotation * local_offset).truncate();
+    let world_pos = transform.translation.truncate() + world_offset;
+    world_to_atom(world_pos).round().as_ivec2()
+}
+
+/// Each frame, re-stamps every awake body's atoms into the grid at its
+/// current transform so the falling-sand simulation keeps colliding with
+/// it, clearing the previous frame's stamp first. Bodies that have gone to
+/// sleep are baked back into static atoms and despawned instead.
+pub fn restamp_rigid_bodies(
+    mut commands: Commands,
+    grid: Query<&Grid>,
+    mut dirty: ResMut<crate::grid::LightDirty>,
+    mut bodies: Query<(Entity, &Transform, &Sleeping, &mut RigidBodyAtoms)>,
+) {
+    let Ok(grid) = grid.get_single() else {
+        return;
+    };
+
+    for (entity, transform, sleeping, mut body) in &mut bodies {
+        // Clear last frame's stamp before re-stamping or baking, so the
+        // body doesn't leave a permanent trail of copies behind it.
+        for &pos in &body.last_stamp {
+            write_atom(grid, pos, Atom::default(), &mut dirty);
+        }
+        body.last_stamp.clear();
+
+        if sleeping.sleeping {
+            for body_atom in &body.atoms {
+                let pos = stamped_pos(transform, body_atom.local);
+                write_atom(grid, pos, body_atom.atom, &mut dirty);
+            }
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        // Stamp unconditionally: the whole point of a rigid body is that
+        // its footprint is solid, so whatever has since flowed into that
+        // cell (sand, water, a stale static atom) gets overwritten rather
+        // than silently skipped - and every stamped cell is tracked so it
+        // gets cleared again next frame.
+        for body_atom in &body.atoms {
+            let pos = stamped_pos(transform, body_atom.local);
+            write_atom(grid, pos, body_atom.atom, &mut dirty);
+            body.last_stamp.insert(pos);
+        }
+    }
+}
+
+fn write_atom(grid: &Grid, pos: IVec2, atom: Atom, dirty: &mut crate::grid::LightDirty) {
+    let chunk_lenght = CHUNK_SIZE as i32;
+    let cpos = CPos::new(pos.x.div_euclid(chunk_lenght), pos.y.div_euclid(chunk_lenght));
+    let local = IVec2::new(pos.x.rem_euclid(chunk_lenght), pos.y.rem_euclid(chunk_lenght));
+
+    let Some(chunk) = grid.chunks.get(&cpos) else {
+        return;
+    };
+    chunk.write().unwrap().atoms[local.d1()] = atom;
+    dirty.0.entry(cpos).or_default().insert(local);
+}
+
+pub struct RigidBodyPlugin;
+impl Plugin for RigidBodyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ExtractionTimer>()
+            .add_system(extract_detached_solids)
+            .add_system(restamp_rigid_bodies.after(extract_detached_solids));
+    }
+}